@@ -0,0 +1,155 @@
+//! Capture/replay a sequence of typed records over a Kafka topic.
+//!
+//! Each record is serialized by a user-supplied encoder into a length-prefixed, sequence
+//! numbered frame before being sent as a single Kafka message, so the crate stays agnostic to
+//! any particular serialization format. A frame's header (payload length + sequence number)
+//! survives Kafka message boundaries, so a record spanning more than one Kafka message, or a
+//! replay that starts mid-record, still reassembles correctly.
+extern crate futures;
+
+use self::futures::{Async, Poll, Stream};
+
+use client::Topic;
+use consumer::ConsumerPollingThread;
+use error::Error;
+use producer::Producer;
+
+const HEADER_LEN: usize = 12; // 4 bytes payload length + 8 bytes sequence number
+
+/// Largest payload a single frame is allowed to declare. Guards against treating misaligned
+/// buffer bytes as a frame header and buffering forever while waiting for a frame that will
+/// never complete.
+const MAX_FRAME_PAYLOAD_LEN: u32 = 16 * 1024 * 1024;
+
+fn encode_header(payload_len: u32, sequence: u64) -> [u8; HEADER_LEN] {
+    let mut header = [0u8; HEADER_LEN];
+    header[0] = (payload_len >> 24) as u8;
+    header[1] = (payload_len >> 16) as u8;
+    header[2] = (payload_len >> 8) as u8;
+    header[3] = payload_len as u8;
+    for i in 0..8 {
+        header[4 + i] = (sequence >> (8 * (7 - i))) as u8;
+    }
+    header
+}
+
+fn decode_header(header: &[u8]) -> (u32, u64) {
+    let payload_len = ((header[0] as u32) << 24) | ((header[1] as u32) << 16) |
+                       ((header[2] as u32) << 8) | (header[3] as u32);
+    let mut sequence = 0u64;
+    for i in 0..8 {
+        sequence = (sequence << 8) | (header[4 + i] as u64);
+    }
+    (payload_len, sequence)
+}
+
+/// Writes a sequence of typed records onto a topic, one length-prefixed frame per record.
+pub struct EventWriter<'a, T> {
+    producer: Producer,
+    topic: Topic<'a>,
+    encode: Box<Fn(&T) -> Vec<u8>>,
+    sequence: u64,
+}
+
+impl<'a, T> EventWriter<'a, T> {
+    /// Creates a new `EventWriter` that encodes each pushed value with `encode` before sending
+    /// it to `topic`.
+    pub fn new<F>(producer: Producer, topic: Topic<'a>, encode: F) -> EventWriter<'a, T>
+        where F: Fn(&T) -> Vec<u8> + 'static {
+        EventWriter {
+            producer: producer,
+            topic: topic,
+            encode: Box::new(encode),
+            sequence: 0,
+        }
+    }
+
+    /// Encodes `value` and sends it as a single framed Kafka message.
+    pub fn push(&mut self, value: &T) -> Result<(), Error> {
+        let payload = (self.encode)(value);
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+        frame.extend_from_slice(&encode_header(payload.len() as u32, self.sequence));
+        frame.extend_from_slice(&payload);
+        self.sequence += 1;
+        try!(self.producer.send_copy(&self.topic, Some(&frame[..]), None::<&[u8]>));
+        Ok(())
+    }
+}
+
+/// Reassembles the frames written by an `EventWriter<T>` back into a `Stream<Item = T>`.
+pub struct EventReader<T> {
+    inner: ConsumerPollingThread,
+    decode: Box<Fn(&[u8]) -> T>,
+    buffer: Vec<u8>,
+    // Sequence number the next decoded frame must carry. `None` until the first frame is read,
+    // since a replay may legitimately start at any sequence number, not just zero.
+    next_sequence: Option<u64>,
+}
+
+impl<T> EventReader<T> {
+    /// Creates a new `EventReader` that decodes reassembled frames from `inner` with `decode`.
+    pub fn new<F>(inner: ConsumerPollingThread, decode: F) -> EventReader<T>
+        where F: Fn(&[u8]) -> T + 'static {
+        EventReader {
+            inner: inner,
+            decode: Box::new(decode),
+            buffer: Vec::new(),
+            next_sequence: None,
+        }
+    }
+
+    /// Pops one fully-buffered frame off the front of `buffer` and decodes it, if a complete
+    /// frame is available yet.
+    ///
+    /// A candidate header is only accepted once its sequence number matches what's expected
+    /// (consecutive from the first frame read) and its declared payload length is sane. If
+    /// either check fails -- which happens when a replay starts mid-frame and `buffer[0]` is no
+    /// longer aligned on a frame boundary -- a single byte is dropped and the candidate header
+    /// is re-read one position over, resyncing onto the next real frame boundary.
+    fn take_buffered_record(&mut self) -> Option<T> {
+        loop {
+            if self.buffer.len() < HEADER_LEN {
+                return None;
+            }
+            let (payload_len, sequence) = decode_header(&self.buffer[..HEADER_LEN]);
+            let in_sequence = match self.next_sequence {
+                Some(expected) => sequence == expected,
+                None => true,
+            };
+            if !in_sequence || payload_len > MAX_FRAME_PAYLOAD_LEN {
+                self.buffer.remove(0);
+                continue;
+            }
+            let frame_len = HEADER_LEN + payload_len as usize;
+            if self.buffer.len() < frame_len {
+                return None;
+            }
+            let record = (self.decode)(&self.buffer[HEADER_LEN..frame_len]);
+            self.buffer.drain(..frame_len);
+            self.next_sequence = Some(sequence + 1);
+            return Some(record);
+        }
+    }
+}
+
+impl<T> Stream for EventReader<T> {
+    type Item = T;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<T>, Error> {
+        loop {
+            if let Some(record) = self.take_buffered_record() {
+                return Ok(Async::Ready(Some(record)));
+            }
+            match try!(self.inner.poll()) {
+                Async::Ready(Some(message)) => {
+                    if let Some(payload) = message.payload() {
+                        self.buffer.extend_from_slice(payload);
+                    }
+                }
+                Async::Ready(None) => return Ok(Async::Ready(None)),
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
@@ -6,6 +6,9 @@ use std::ffi::CString;
 use std::os::raw::c_void;
 use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::slice;
+use std::str;
+use std::sync::Mutex;
 
 use self::futures::Complete;
 
@@ -13,6 +16,10 @@ use config::Config;
 use error::{IsError, Error};
 use util::cstr_to_owned;
 
+/// Type of the user-supplied closure that receives the periodic JSON statistics document
+/// emitted by librdkafka. See `Client::set_stats_callback`.
+type StatsCallback = Box<FnMut(&str) + Send>;
+
 /// Specifies the type of client.
 pub enum ClientType {
     /// A librdkafka consumer
@@ -24,6 +31,7 @@ pub enum ClientType {
 /// A librdkafka client.
 pub struct Client {
     pub ptr: *mut rdkafka::rd_kafka_t,
+    stats_callback: Box<Mutex<Option<StatsCallback>>>,
 }
 
 unsafe impl Sync for Client {}
@@ -38,20 +46,45 @@ pub struct DeliveryStatus {
     offset: i64,
 }
 
+impl DeliveryStatus {
+    /// Creates a new `DeliveryStatus` from the result fields of a delivered `rd_kafka_message_t`.
+    pub fn new(error: rdkafka::rd_kafka_resp_err_t, partition: i32, offset: i64) -> DeliveryStatus {
+        DeliveryStatus {
+            error: error,
+            partition: partition,
+            offset: offset,
+        }
+    }
+}
+
 unsafe extern "C" fn prod_callback(_client: *mut rdkafka::rd_kafka_t,
                                    msg: *const rdkafka::rd_kafka_message_t,
                                    _opaque: *mut c_void) {
     let tx = Box::from_raw((*msg)._private as *mut Complete<DeliveryStatus>);
-    let delivery_status = DeliveryStatus {
-        error: (*msg).err,
-        partition: (*msg).partition,
-        offset: (*msg).offset,
-    };
+    let delivery_status = DeliveryStatus::new((*msg).err, (*msg).partition, (*msg).offset);
     // TODO: add topic name?
     trace!("Delivery event received: {:?}", delivery_status);
     tx.complete(delivery_status);
 }
 
+unsafe extern "C" fn stats_callback(_client: *mut rdkafka::rd_kafka_t,
+                                    json: *mut i8,
+                                    json_len: usize,
+                                    opaque: *mut c_void) -> i32 {
+    let json_slice = slice::from_raw_parts(json as *const u8, json_len);
+    if let Ok(json_str) = str::from_utf8(json_slice) {
+        let callback = &*(opaque as *const Mutex<Option<StatsCallback>>);
+        if let Ok(mut guard) = callback.lock() {
+            if let Some(ref mut cb) = *guard {
+                cb(json_str);
+            }
+        }
+    } else {
+        warn!("Received non UTF-8 statistics JSON from librdkafka");
+    }
+    0
+}
+
 impl Client {
     pub fn new(config: &Config, client_type: ClientType) -> Result<Client, Error> {
         let errstr = [0i8; 1024];
@@ -63,12 +96,28 @@ impl Client {
                 rdkafka::rd_kafka_type_t::RD_KAFKA_PRODUCER
             }
         };
+        let stats_callback_box = Box::new(Mutex::new(None));
+        if config.get("statistics.interval.ms").is_some() {
+            let opaque_ptr = &*stats_callback_box as *const Mutex<Option<StatsCallback>> as *mut c_void;
+            unsafe {
+                rdkafka::rd_kafka_conf_set_opaque(config_ptr, opaque_ptr);
+                rdkafka::rd_kafka_conf_set_stats_cb(config_ptr, Some(stats_callback));
+            }
+        }
         let client_ptr =
             unsafe { rdkafka::rd_kafka_new(rd_kafka_type, config_ptr, errstr.as_ptr() as *mut i8, errstr.len()) };
         if client_ptr.is_null() {
             return Err(Error::ClientCreation(cstr_to_owned(&errstr)));
         }
-        Ok(Client { ptr: client_ptr })
+        Ok(Client { ptr: client_ptr, stats_callback: stats_callback_box })
+    }
+
+    /// Install a closure that will be called with the JSON statistics document librdkafka emits
+    /// every `statistics.interval.ms`. Requires `statistics.interval.ms` to have been set on the
+    /// `Config` used to create this client, otherwise librdkafka never produces the callback.
+    pub fn set_stats_callback<F>(&self, callback: F)
+        where F: FnMut(&str) + Send + 'static {
+        *self.stats_callback.lock().unwrap() = Some(Box::new(callback));
     }
 }
 
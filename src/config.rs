@@ -25,6 +25,11 @@ impl Config {
         self
     }
 
+    /// Returns the value previously set for `key`, if any.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.conf.get(key).map(|v| v.as_str())
+    }
+
     /// Creates the native rdkafka configuration.
     pub fn create_kafka_config(&self) -> Result<*mut rdkafka::rd_kafka_conf_t, Error> {
         let conf = unsafe { rdkafka::rd_kafka_conf_new() };
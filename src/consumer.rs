@@ -0,0 +1,290 @@
+//! Consumer implementations.
+extern crate librdkafka_sys as rdkafka;
+extern crate futures;
+
+use self::futures::{Async, Poll, Stream};
+use self::futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+
+use std::ffi::CString;
+use std::slice;
+use std::str;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::thread;
+
+use config::CreateConsumer;
+use config::Config;
+use error::{Error, IsError};
+use client::{Client, ClientType};
+
+/// Contains a reference counted consumer client. It can be safely cloned to
+/// create another reference to the same consumer.
+#[derive(Clone)]
+pub struct Consumer {
+    client: Arc<Client>,
+}
+
+impl CreateConsumer<Consumer, Error> for Config {
+    fn create_consumer(&self) -> Result<Consumer, Error> {
+        let client = try!(Client::new(&self, ClientType::Consumer));
+        // Route fetched messages onto the consumer queue so that `rd_kafka_consumer_poll`
+        // (rather than just `rd_kafka_poll`) actually receives them.
+        unsafe { rdkafka::rd_kafka_poll_set_consumer(client.ptr) };
+        let consumer = Consumer { client: Arc::new(client) };
+        Ok(consumer)
+    }
+}
+
+/// A message received from the cluster through a `Consumer`. Owns the underlying
+/// `rd_kafka_message_t` and destroys it on drop.
+pub struct Message {
+    ptr: *mut rdkafka::rd_kafka_message_t,
+}
+
+unsafe impl Send for Message {}
+
+impl Message {
+    /// Returns the message payload.
+    pub fn payload(&self) -> Option<&[u8]> {
+        unsafe {
+            let ptr = (*self.ptr).payload;
+            if ptr.is_null() {
+                None
+            } else {
+                Some(slice::from_raw_parts(ptr as *const u8, (*self.ptr).len))
+            }
+        }
+    }
+
+    /// Returns the message key.
+    pub fn key(&self) -> Option<&[u8]> {
+        unsafe {
+            let ptr = (*self.ptr).key;
+            if ptr.is_null() {
+                None
+            } else {
+                Some(slice::from_raw_parts(ptr as *const u8, (*self.ptr).key_len))
+            }
+        }
+    }
+
+    /// Returns the topic the message was received from.
+    pub fn topic(&self) -> &str {
+        unsafe {
+            let name_ptr = rdkafka::rd_kafka_topic_name((*self.ptr).rkt);
+            str::from_utf8(std::ffi::CStr::from_ptr(name_ptr).to_bytes()).unwrap_or("")
+        }
+    }
+
+    /// Returns the partition the message was received from.
+    pub fn partition(&self) -> i32 {
+        unsafe { (*self.ptr).partition }
+    }
+
+    /// Returns the offset of the message within the partition.
+    pub fn offset(&self) -> i64 {
+        unsafe { (*self.ptr).offset }
+    }
+}
+
+impl Drop for Message {
+    fn drop(&mut self) {
+        trace!("Destroy rd_kafka_message");
+        unsafe { rdkafka::rd_kafka_message_destroy(self.ptr) };
+    }
+}
+
+impl Consumer {
+    /// Subscribe the consumer to the given list of topics.
+    pub fn subscribe(&self, topics: &[&str]) -> Result<(), Error> {
+        let tp_list = unsafe { rdkafka::rd_kafka_topic_partition_list_new(topics.len() as i32) };
+        for topic in topics {
+            let topic_c = try!(CString::new(*topic));
+            unsafe { rdkafka::rd_kafka_topic_partition_list_add(tp_list, topic_c.as_ptr(), -1) };
+        }
+        let ret = unsafe { rdkafka::rd_kafka_subscribe(self.client.ptr, tp_list) };
+        unsafe { rdkafka::rd_kafka_topic_partition_list_destroy(tp_list) };
+        if ret.is_error() {
+            Err(Error::Subscription(format!("{:?}", topics)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Poll the consumer for new messages. Blocks for at most `timeout_ms` milliseconds.
+    /// Returns `None` if no message was available within the timeout. `RD_KAFKA_RESP_ERR__PARTITION_EOF`
+    /// is not a real error -- it just means a partition has been fully consumed -- so it is
+    /// swallowed here and reported as "no message" rather than as an `Err`.
+    pub fn poll(&self, timeout_ms: i32) -> Option<Result<Message, Error>> {
+        let msg_ptr = unsafe { rdkafka::rd_kafka_consumer_poll(self.client.ptr, timeout_ms) };
+        if msg_ptr.is_null() {
+            return None;
+        }
+        let err = unsafe { (*msg_ptr).err };
+        if err == rdkafka::rd_kafka_resp_err_t::RD_KAFKA_RESP_ERR__PARTITION_EOF {
+            unsafe { rdkafka::rd_kafka_message_destroy(msg_ptr) };
+            return None;
+        }
+        if err.is_error() {
+            unsafe { rdkafka::rd_kafka_message_destroy(msg_ptr) };
+            Some(Err(Error::MessageConsumption(err)))
+        } else {
+            Some(Ok(Message { ptr: msg_ptr }))
+        }
+    }
+
+    /// Commit the current consumer offsets synchronously.
+    pub fn commit(&self) -> Result<(), Error> {
+        let ret = unsafe {
+            rdkafka::rd_kafka_commit(self.client.ptr, std::ptr::null_mut(), 0)
+        };
+        if ret.is_error() {
+            Err(Error::MessageConsumption(ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Commit exactly one `(topic, partition, offset)`, rather than every stored offset like
+    /// `commit` does. Used by the DLQ stream to advance past a diverted message without also
+    /// committing other messages still buffered, unprocessed, on the polling thread's channel.
+    pub fn commit_message(&self, topic: &str, partition: i32, offset: i64) -> Result<(), Error> {
+        let topic_c = try!(CString::new(topic));
+        let tp_list = unsafe { rdkafka::rd_kafka_topic_partition_list_new(1) };
+        let tp = unsafe { rdkafka::rd_kafka_topic_partition_list_add(tp_list, topic_c.as_ptr(), partition) };
+        unsafe { (*tp).offset = offset };
+        let ret = unsafe { rdkafka::rd_kafka_commit(self.client.ptr, tp_list, 0) };
+        unsafe { rdkafka::rd_kafka_topic_partition_list_destroy(tp_list) };
+        if ret.is_error() {
+            Err(Error::MessageConsumption(ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Close the consumer, leaving the consumer group and committing final offsets.
+    pub fn close(&self) -> Result<(), Error> {
+        let ret = unsafe { rdkafka::rd_kafka_consumer_close(self.client.ptr) };
+        if ret.is_error() {
+            Err(Error::MessageConsumption(ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Start the polling thread for the consumer. It returns a `ConsumerPollingThread` that
+    /// yields a `futures::Stream` of the received messages. Calling `poll` directly is not
+    /// required if the `ConsumerPollingThread` thread is running.
+    pub fn start_polling_thread(&self) -> ConsumerPollingThread {
+        let mut threaded_consumer = ConsumerPollingThread::new(self);
+        threaded_consumer.start();
+        threaded_consumer
+    }
+
+    /// Install a closure that will be called with each JSON statistics document emitted by
+    /// librdkafka. Requires `statistics.interval.ms` to have been set on the `Config` this
+    /// consumer was created from.
+    pub fn set_stats_callback<F>(&self, callback: F)
+        where F: FnMut(&str) + Send + 'static {
+        self.client.set_stats_callback(callback);
+    }
+}
+
+/// A consumer with an internal running thread that polls `rd_kafka_consumer_poll` and forwards
+/// decoded messages into a `futures::Stream`. The internal thread can be terminated with the
+/// `stop` method or by moving the `ConsumerPollingThread` out of scope.
+#[must_use = "Consumer polling thread will stop immediately if unused"]
+pub struct ConsumerPollingThread {
+    consumer: Consumer,
+    should_stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    rx: UnboundedReceiver<Result<Message, Error>>,
+    tx: Option<UnboundedSender<Result<Message, Error>>>,
+}
+
+impl ConsumerPollingThread {
+    /// Creates a new `ConsumerPollingThread`. The internal thread will not be running yet.
+    pub fn new(consumer: &Consumer) -> ConsumerPollingThread {
+        let (tx, rx) = unbounded();
+        ConsumerPollingThread {
+            consumer: consumer.clone(),
+            should_stop: Arc::new(AtomicBool::new(false)),
+            handle: None,
+            rx: rx,
+            tx: Some(tx),
+        }
+    }
+
+    /// Start the internal polling thread.
+    pub fn start(&mut self) {
+        let consumer = self.consumer.clone();
+        let should_stop = self.should_stop.clone();
+        let tx = self.tx.take().expect("Polling thread already started");
+        let handle = thread::Builder::new()
+            .name("consumer polling thread".to_string())
+            .spawn(move || {
+                trace!("Consumer polling thread loop started");
+                while !should_stop.load(Ordering::Relaxed) {
+                    if let Some(message) = consumer.poll(100) {
+                        if tx.unbounded_send(message).is_err() {
+                            break;
+                        }
+                    }
+                }
+                unsafe { rdkafka::rd_kafka_consumer_close(consumer.client.ptr) };
+                trace!("Consumer polling thread loop terminated");
+            })
+            .expect("Failed to start consumer polling thread");
+        self.handle = Some(handle);
+    }
+
+    /// Stop the internal polling thread. The thread can also be stopped by moving
+    /// the ConsumerPollingThread out of scope.
+    pub fn stop(&mut self) {
+        if self.handle.is_some() {
+            trace!("Stopping consumer polling");
+            self.should_stop.store(true, Ordering::Relaxed);
+            trace!("Waiting for consumer polling thread termination");
+            match self.handle.take().unwrap().join() {
+                Ok(()) => trace!("Consumer polling stopped"),
+                Err(e) => warn!("Failure while terminating thread: {:?}", e),
+            };
+        }
+    }
+
+    /// Commit the current consumer offsets synchronously.
+    pub fn commit(&self) -> Result<(), Error> {
+        self.consumer.commit()
+    }
+
+    /// Commit exactly one `(topic, partition, offset)`. See `Consumer::commit_message`.
+    pub fn commit_message(&self, topic: &str, partition: i32, offset: i64) -> Result<(), Error> {
+        self.consumer.commit_message(topic, partition, offset)
+    }
+}
+
+impl Stream for ConsumerPollingThread {
+    type Item = Message;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Message>, Error> {
+        // `UnboundedReceiver` parks the current task and is woken up by the sender on the
+        // polling thread, unlike a plain `std::sync::mpsc` channel which has no hook into the
+        // futures task system.
+        match self.rx.poll() {
+            Ok(Async::Ready(Some(Ok(message)))) => Ok(Async::Ready(Some(message))),
+            Ok(Async::Ready(Some(Err(e)))) => Err(e),
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(()) => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+impl Drop for ConsumerPollingThread {
+    fn drop(&mut self) {
+        trace!("Destroy ConsumerPollingThread");
+        self.stop();
+    }
+}
@@ -0,0 +1,215 @@
+//! Dead-letter-queue handling for the consumer.
+extern crate librdkafka_sys as rdkafka;
+extern crate futures;
+
+use self::futures::{Async, Poll, Stream};
+
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+
+use consumer::{ConsumerPollingThread, Message};
+use error::{Error, IsError};
+use producer::Producer;
+
+/// Configures how a `DlqStream` reacts to a message whose handler keeps failing.
+///
+/// Messages that still fail processing after `max_retries` retries (`max_retries + 1` attempts
+/// in all) are re-produced to `dlq_topic`
+/// (original payload and key, plus headers recording the source topic/partition/offset and the
+/// error) and their offset is committed so the main stream can keep advancing instead of
+/// getting stuck behind a single poisoned message.
+pub struct DlqPolicy {
+    producer: Producer,
+    // The DLQ topic's raw handle, created once in `new` and destroyed in `Drop`, rather than
+    // recreated through a `Topic` on every diverted message. `Topic<'a>` borrows `&'a Client`,
+    // which doesn't fit a struct that also owns the `Producer` the client comes from, so the
+    // pointer is held directly and its lifetime managed by hand, same as `Topic` itself does.
+    dlq_topic_ptr: *mut rdkafka::rd_kafka_topic_t,
+    max_retries: u32,
+}
+
+unsafe impl Send for DlqPolicy {}
+unsafe impl Sync for DlqPolicy {}
+
+impl DlqPolicy {
+    /// Creates a new dead-letter-queue policy, creating the underlying `dlq_topic` handle.
+    pub fn new(producer: Producer, dlq_topic: &str, max_retries: u32) -> Result<DlqPolicy, Error> {
+        let dlq_topic_ptr = {
+            let topic = try!(producer.get_topic(dlq_topic).create());
+            let ptr = topic.ptr;
+            // The pointer now lives on in `dlq_topic_ptr`; don't let `topic`'s `Drop` destroy it.
+            mem::forget(topic);
+            ptr
+        };
+        Ok(DlqPolicy {
+            producer: producer,
+            dlq_topic_ptr: dlq_topic_ptr,
+            max_retries: max_retries,
+        })
+    }
+
+    fn send_to_dlq(&self, message: &Message, error: &str) -> Result<(), Error> {
+        let headers = unsafe { rdkafka::rd_kafka_headers_new(4) };
+        if let Err(e) = build_headers(headers, message, error) {
+            unsafe { rdkafka::rd_kafka_headers_destroy(headers) };
+            return Err(e);
+        }
+        let result = produce_with_headers(self.producer.client_ptr(), self.dlq_topic_ptr,
+                                          message.payload(), message.key(), headers);
+        if result.is_err() {
+            // `rd_kafka_produceva` only takes ownership of `headers` once the message is
+            // successfully enqueued; on failure it's still ours to destroy.
+            unsafe { rdkafka::rd_kafka_headers_destroy(headers) };
+        }
+        result
+    }
+}
+
+impl Drop for DlqPolicy {
+    fn drop(&mut self) {
+        trace!("Destroy dlq topic");
+        unsafe { rdkafka::rd_kafka_topic_destroy(self.dlq_topic_ptr) };
+    }
+}
+
+fn build_headers(headers: *mut rdkafka::rd_kafka_headers_t, message: &Message, error: &str) -> Result<(), Error> {
+    try!(add_header(headers, "source_topic", message.topic().as_bytes()));
+    try!(add_header(headers, "source_partition", message.partition().to_string().as_bytes()));
+    try!(add_header(headers, "source_offset", message.offset().to_string().as_bytes()));
+    try!(add_header(headers, "error", error.as_bytes()));
+    Ok(())
+}
+
+fn add_header(headers: *mut rdkafka::rd_kafka_headers_t, name: &str, value: &[u8]) -> Result<(), Error> {
+    let ret = unsafe {
+        rdkafka::rd_kafka_header_add(headers,
+                                     name.as_ptr() as *const i8,
+                                     name.len() as isize,
+                                     value.as_ptr() as *const c_void,
+                                     value.len() as isize)
+    };
+    if ret.is_error() {
+        Err(Error::MessageProduction(ret))
+    } else {
+        Ok(())
+    }
+}
+
+fn produce_with_headers(client_ptr: *mut rdkafka::rd_kafka_t,
+                        topic_ptr: *mut rdkafka::rd_kafka_topic_t,
+                        payload: Option<&[u8]>,
+                        key: Option<&[u8]>,
+                        headers: *mut rdkafka::rd_kafka_headers_t)
+                        -> Result<(), Error> {
+    let (payload_n, plen) = match payload {
+        None => (ptr::null_mut(), 0),
+        Some(p) => (p.as_ptr() as *mut c_void, p.len()),
+    };
+    let (key_n, klen) = match key {
+        None => (ptr::null_mut(), 0),
+        Some(k) => (k.as_ptr() as *mut c_void, k.len()),
+    };
+    let vus = [
+        rdkafka::rd_kafka_vu_t {
+            vtype: rdkafka::rd_kafka_vtype_t::RD_KAFKA_VTYPE_RKT,
+            u: rdkafka::rd_kafka_vu_t_u { rkt: topic_ptr },
+        },
+        rdkafka::rd_kafka_vu_t {
+            vtype: rdkafka::rd_kafka_vtype_t::RD_KAFKA_VTYPE_VALUE,
+            u: rdkafka::rd_kafka_vu_t_u { mem: rdkafka::rd_kafka_vu_t_mem { ptr: payload_n, size: plen } },
+        },
+        rdkafka::rd_kafka_vu_t {
+            vtype: rdkafka::rd_kafka_vtype_t::RD_KAFKA_VTYPE_KEY,
+            u: rdkafka::rd_kafka_vu_t_u { mem: rdkafka::rd_kafka_vu_t_mem { ptr: key_n, size: klen } },
+        },
+        rdkafka::rd_kafka_vu_t {
+            vtype: rdkafka::rd_kafka_vtype_t::RD_KAFKA_VTYPE_HEADERS,
+            u: rdkafka::rd_kafka_vu_t_u { headers: headers },
+        },
+        rdkafka::rd_kafka_vu_t {
+            vtype: rdkafka::rd_kafka_vtype_t::RD_KAFKA_VTYPE_MSGFLAGS,
+            u: rdkafka::rd_kafka_vu_t_u { msgflags: rdkafka::RD_KAFKA_MSG_F_COPY as i32 },
+        },
+    ];
+    let ret = unsafe { rdkafka::rd_kafka_produceva(client_ptr, vus.as_ptr(), vus.len()) };
+    if ret.is_error() {
+        Err(Error::MessageProduction(ret))
+    } else {
+        Ok(())
+    }
+}
+
+/// A `Stream` combinator that routes a message whose `handler` keeps failing into a
+/// dead-letter topic after `DlqPolicy::max_retries` retries (`max_retries + 1` attempts in all),
+/// instead of letting a single poisoned message block progress on the partition.
+///
+/// Unlike a naive "count failures per offset and move on" approach, a failed message is held
+/// and retried against the *same* `Message`, immediately and within the same `poll` call, until
+/// either the handler succeeds or the retry budget is exhausted -- the underlying consumer is
+/// never asked for a new message while a retry is outstanding.
+pub struct DlqStream<F> {
+    inner: ConsumerPollingThread,
+    handler: F,
+    policy: DlqPolicy,
+    pending: Option<(Message, u32)>,
+}
+
+impl<F> DlqStream<F>
+    where F: FnMut(&Message) -> Result<(), String> {
+    /// Wraps `inner` so that a message whose `handler` keeps failing is diverted to the
+    /// dead-letter topic described by `policy`.
+    pub fn new(inner: ConsumerPollingThread, handler: F, policy: DlqPolicy) -> DlqStream<F> {
+        DlqStream {
+            inner: inner,
+            handler: handler,
+            policy: policy,
+            pending: None,
+        }
+    }
+}
+
+impl<F> Stream for DlqStream<F>
+    where F: FnMut(&Message) -> Result<(), String> {
+    type Item = Message;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Message>, Error> {
+        loop {
+            let (message, attempts) = match self.pending.take() {
+                Some(pending) => pending,
+                None => {
+                    match try!(self.inner.poll()) {
+                        Async::Ready(Some(message)) => (message, 0),
+                        Async::Ready(None) => return Ok(Async::Ready(None)),
+                        Async::NotReady => return Ok(Async::NotReady),
+                    }
+                }
+            };
+            match (self.handler)(&message) {
+                Ok(()) => return Ok(Async::Ready(Some(message))),
+                Err(err) => {
+                    let attempts = attempts + 1;
+                    if attempts > self.policy.max_retries {
+                        try!(self.policy.send_to_dlq(&message, &err));
+                        // Commit only this message's offset, not `self.inner.commit()`'s view of
+                        // "every stored offset" -- the polling thread reads ahead and buffers
+                        // later, still-unprocessed messages on its channel, and committing past
+                        // those would lose them on a crash.
+                        try!(self.inner.commit_message(message.topic(), message.partition(), message.offset() + 1));
+                        return Ok(Async::Ready(Some(message)));
+                    }
+                    // Retry budget not exhausted yet: hold onto this exact message and retry
+                    // the handler against it again on the next loop iteration.
+                    self.pending = Some((message, attempts));
+                }
+            }
+        }
+    }
+}
+
+/// Wraps a consumer stream with a dead-letter-queue policy. See `DlqStream`.
+pub fn with_dlq<F>(inner: ConsumerPollingThread, handler: F, policy: DlqPolicy) -> DlqStream<F>
+    where F: FnMut(&Message) -> Result<(), String> {
+    DlqStream::new(inner, handler, policy)
+}
@@ -3,6 +3,9 @@ extern crate libc;
 extern crate librdkafka_sys as rdkafka;
 extern crate std;
 
+use std::ffi::CStr;
+use std::fmt;
+
 /// Response error
 pub type RespError = rdkafka::rd_kafka_resp_err_t;
 
@@ -27,6 +30,48 @@ impl IsError for ConfRes {
     }
 }
 
+fn cstr_to_str(ptr: *const libc::c_char) -> &'static str {
+    if ptr.is_null() {
+        return "";
+    }
+    unsafe { CStr::from_ptr(ptr).to_str().unwrap_or("") }
+}
+
+/// A thin, `Copy`-able wrapper around a librdkafka `RespError` that exposes its numeric code,
+/// its short machine name and its human-readable description. Use this instead of matching on
+/// the raw `RespError` discriminant when the specific error condition matters to the caller
+/// (e.g. partition EOF, queue full).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KafkaError(RespError);
+
+impl KafkaError {
+    /// Wraps a raw `RespError` code.
+    pub fn new(err: RespError) -> KafkaError {
+        KafkaError(err)
+    }
+
+    /// The numeric librdkafka error code.
+    pub fn code(&self) -> i32 {
+        self.0 as i32
+    }
+
+    /// The short, machine-readable error name (e.g. `"NO_ERROR"`, without the `RD_KAFKA_RESP_ERR_` prefix).
+    pub fn name(&self) -> &'static str {
+        cstr_to_str(unsafe { rdkafka::rd_kafka_err2name(self.0) })
+    }
+
+    /// The human-readable error description.
+    pub fn description(&self) -> &'static str {
+        cstr_to_str(unsafe { rdkafka::rd_kafka_err2str(self.0) })
+    }
+}
+
+impl fmt::Display for KafkaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.description(), self.name())
+    }
+}
+
 #[derive(Debug)]
 /// Represents all Kafka errors.
 pub enum Error {
@@ -45,3 +90,43 @@ impl From<std::ffi::NulError> for Error {
         Error::Nul(err)
     }
 }
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Config((conf_res, ref descr, ref key, ref value)) => {
+                write!(f, "Configuration error setting \"{}\" = \"{}\": {} ({:?})",
+                       key, value, descr, conf_res)
+            }
+            Error::ConsumerCreation(ref descr) => write!(f, "Failed to create consumer: {}", descr),
+            Error::ClientCreation(ref descr) => write!(f, "Failed to create client: {}", descr),
+            Error::MessageConsumption(err) => write!(f, "Failed to consume message: {}", KafkaError::new(err)),
+            Error::MessageProduction(err) => write!(f, "Failed to produce message: {}", KafkaError::new(err)),
+            Error::Subscription(ref descr) => write!(f, "Failed to subscribe to topics: {}", descr),
+            Error::TopicName(ref name) => write!(f, "Failed to create topic: {}", name),
+            Error::Nul(ref err) => write!(f, "Nul byte found in provided string: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Config(..) => "configuration error",
+            Error::ConsumerCreation(..) => "failed to create consumer",
+            Error::ClientCreation(..) => "failed to create client",
+            Error::MessageConsumption(..) => "failed to consume message",
+            Error::MessageProduction(..) => "failed to produce message",
+            Error::Subscription(..) => "failed to subscribe to topics",
+            Error::TopicName(..) => "failed to create topic",
+            Error::Nul(..) => "nul byte found in provided string",
+        }
+    }
+
+    fn cause(&self) -> Option<&std::error::Error> {
+        match *self {
+            Error::Nul(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
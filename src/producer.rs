@@ -3,7 +3,7 @@ extern crate librdkafka_sys as rdkafka;
 extern crate errno;
 extern crate futures;
 
-use self::futures::{Canceled, Future, Poll, Oneshot};
+use self::futures::{Canceled, Complete, Future, Poll, Oneshot};
 
 use std::os::raw::c_void;
 use std::ptr;
@@ -14,7 +14,7 @@ use std::thread;
 
 use config::CreateProducer;
 use config::Config;
-use error::Error;
+use error::{Error, IsError};
 use message::ToBytes;
 use client::{Client, ClientType, TopicBuilder, Topic, DeliveryStatus};
 
@@ -55,6 +55,12 @@ impl Producer {
         TopicBuilder::new(&self.client, topic_name)
     }
 
+    /// Return the raw `rd_kafka_t` handle backing this producer, for callers (e.g. the `dlq`
+    /// module) that need to drive lower-level librdkafka APIs such as `rd_kafka_produceva`.
+    pub fn client_ptr(&self) -> *mut rdkafka::rd_kafka_t {
+        self.client.ptr
+    }
+
     /// Poll the producer. Regular calls to `poll` are required to process the evens
     /// and execute the message delivery callbacks.
     pub fn poll(&self, timeout_ms: i32) -> i32 {
@@ -92,6 +98,55 @@ impl Producer {
         self._send_copy(topic, payload.map(P::to_bytes), key.map(K::to_bytes))
     }
 
+    /// Send a batch of payload/key pairs to the given topic in a single `rd_kafka_produce_batch`
+    /// call. Returns one `DeliveryFuture` per input message, in the same order. A message that
+    /// librdkafka rejects up front (queue full, message too large, ...) resolves its future
+    /// immediately with the corresponding error instead of waiting on the delivery callback.
+    pub fn send_batch(&self, topic: &Topic, messages: &[(Option<&[u8]>, Option<&[u8]>)]) -> Vec<DeliveryFuture> {
+        let mut rx_list = Vec::with_capacity(messages.len());
+        let mut rk_messages = Vec::with_capacity(messages.len());
+        for &(payload, key) in messages {
+            let (payload_n, plen) = match payload {
+                None => (ptr::null_mut(), 0),
+                Some(p) => (p.as_ptr() as *mut c_void, p.len()),
+            };
+            let (key_n, klen) = match key {
+                None => (ptr::null_mut(), 0),
+                Some(k) => (k.as_ptr() as *mut c_void, k.len()),
+            };
+            let (tx, rx) = futures::oneshot();
+            rx_list.push(rx);
+            let boxed_tx = Box::new(tx);
+            rk_messages.push(rdkafka::rd_kafka_message_t {
+                err: rdkafka::rd_kafka_resp_err_t::RD_KAFKA_RESP_ERR_NO_ERROR,
+                rkt: topic.ptr,
+                partition: rdkafka::RD_KAFKA_PARTITION_UA,
+                payload: payload_n,
+                len: plen,
+                key: key_n,
+                key_len: klen,
+                offset: 0,
+                _private: Box::into_raw(boxed_tx) as *mut c_void,
+            });
+        }
+        unsafe {
+            rdkafka::rd_kafka_produce_batch(topic.ptr, rdkafka::RD_KAFKA_PARTITION_UA,
+                                            rdkafka::RD_KAFKA_MSG_F_COPY as i32,
+                                            rk_messages.as_mut_ptr(), rk_messages.len() as i32);
+        }
+        rx_list.into_iter()
+               .zip(rk_messages.into_iter())
+               .map(|(rx, rk_message)| {
+                   if rk_message.err.is_error() {
+                       let tx = unsafe { Box::from_raw(rk_message._private as *mut Complete<DeliveryStatus>) };
+                       let delivery_status = DeliveryStatus::new(rk_message.err, rk_message.partition, rk_message.offset);
+                       tx.complete(delivery_status);
+                   }
+                   DeliveryFuture { rx: rx }
+               })
+               .collect()
+    }
+
     /// Start the polling thread for the producer. It returns a `ProducerPollingThread` that will
     /// process al the events. Calling `poll` is not required if the `ProducerPollingThread`
     /// thread is running.
@@ -100,6 +155,14 @@ impl Producer {
         threaded_producer.start();
         threaded_producer
     }
+
+    /// Install a closure that will be called with each JSON statistics document emitted by
+    /// librdkafka. Requires `statistics.interval.ms` to have been set on the `Config` this
+    /// producer was created from.
+    pub fn set_stats_callback<F>(&self, callback: F)
+        where F: FnMut(&str) + Send + 'static {
+        self.client.set_stats_callback(callback);
+    }
 }
 
 